@@ -1,7 +1,8 @@
 use bevy::{app::Events, input::system::exit_on_esc_system, prelude::*};
 use bevy_prototype_character_controller::{
     controller::{
-        BodyTag, CameraTag, CharacterController, CharacterControllerPlugin, HeadTag, Mass, YawTag,
+        BodyTag, CameraTag, CharacterController, CharacterControllerPlugin, Grounded, HeadTag,
+        Mass, PreviousVelocity, YawTag,
     },
     events::{ControllerEvents, TranslationEvent},
     look::{LookDirection, LookEntity},
@@ -168,6 +169,8 @@ pub fn spawn_character(
             0.0,
         )),
         CharacterController::default(),
+        PreviousVelocity::default(),
+        Grounded::default(),
         PhysXMaterialDesc {
             static_friction: 0.5,
             dynamic_friction: 0.5,
@@ -13,12 +13,62 @@ use crate::{
     look::{forward_up, input_to_look, LookDirection, LookEntity, MouseMotionState, MouseSettings},
 };
 use bevy::{app::Events, prelude::*};
+use bevy_rapier3d::prelude::{InteractionGroups, QueryPipeline, RigidBodyPosition};
 
 pub struct BodyTag;
 pub struct YawTag;
 pub struct HeadTag;
 pub struct CameraTag;
 
+/// Remembers the character's velocity from the previous simulation step so
+/// the anti-tunneling sweep has a direction and magnitude to cast along even
+/// when `CharacterController.velocity` has already been overwritten for the
+/// current step.
+#[derive(Debug, Default)]
+pub struct PreviousVelocity(pub Vec3);
+
+/// Latched while a character is being constrained to a surface it swept into
+/// to stop it from tunnelling through on the following frames, which would
+/// otherwise show up as jitter as the character alternately tunnels and gets
+/// pushed back out.
+#[derive(Debug)]
+pub struct Tunneling {
+    pub frames: usize,
+    pub dir: Vec3,
+}
+
+impl Default for Tunneling {
+    fn default() -> Self {
+        Self {
+            frames: 15,
+            dir: Vec3::ZERO,
+        }
+    }
+}
+
+/// Result of the downward ground query performed each step. `ground_normal`
+/// is only meaningful while `on_ground` is `true`.
+#[derive(Debug, Default)]
+pub struct Grounded {
+    pub on_ground: bool,
+    pub ground_normal: Vec3,
+    pub last_ground_time: f32,
+}
+
+/// Obstacles shorter than this can be climbed by stepping up onto them
+/// rather than being treated as a wall, so characters can walk up stairs.
+pub struct GlobalStep(pub f32);
+
+impl Default for GlobalStep {
+    fn default() -> Self {
+        Self(0.3)
+    }
+}
+
+/// How long after leaving the ground a jump is still allowed, so a jump
+/// input that lands a frame or two after walking off a ledge still fires.
+pub const COYOTE_TIME: f32 = 0.15;
+
 pub struct CharacterControllerPlugin;
 
 pub const PROCESS_INPUT_EVENTS: &str = "process_input_events";
@@ -35,12 +85,27 @@ impl Plugin for CharacterControllerPlugin {
             .init_resource::<ControllerEvents>()
             .init_resource::<MouseMotionState>()
             .init_resource::<MouseSettings>()
+            .init_resource::<GlobalStep>()
             .add_stage_after(
                 bevy::app::CoreStage::PreUpdate,
                 PROCESS_INPUT_EVENTS,
                 SystemStage::parallel(),
             )
-            .add_system_to_stage(PROCESS_INPUT_EVENTS, input_to_events.system())
+            .add_system_to_stage(
+                PROCESS_INPUT_EVENTS,
+                detect_ground.system().label("detect_ground"),
+            )
+            .add_system_to_stage(
+                PROCESS_INPUT_EVENTS,
+                input_to_events
+                    .system()
+                    .label("input_to_events")
+                    .after("detect_ground"),
+            )
+            .add_system_to_stage(
+                PROCESS_INPUT_EVENTS,
+                mitigate_tunneling.system().after("input_to_events"),
+            )
             .add_system_to_stage(PROCESS_INPUT_EVENTS, input_to_look.system())
             .add_system_to_stage(PROCESS_INPUT_EVENTS, forward_up.system());
     }
@@ -54,6 +119,13 @@ pub struct InputState {
     pub right: bool,
     pub run: bool,
     pub jump: bool,
+    /// Analog movement from a gamepad stick, in the `(right, forward)`
+    /// plane, already deadzoned but not yet clamped to length 1. Zero when
+    /// movement came from (digital) keyboard input instead.
+    pub analog_move: Vec2,
+    /// How far a gamepad run trigger/button is pressed, from `0.0` to `1.0`,
+    /// used to blend between `walk_speed` and `run_speed`.
+    pub analog_run: f32,
 }
 
 pub struct CharacterController {
@@ -67,6 +139,49 @@ pub struct CharacterController {
     pub dt: f32,
     pub sim_to_render: f32,
     pub input_state: InputState,
+    /// Capsule radius used to decide whether the previous step's motion
+    /// (`PreviousVelocity` times `dt`) is large enough to risk tunnelling
+    /// through thin colliders.
+    pub radius: f32,
+    /// Half the height of the capsule's cylindrical section (excluding the
+    /// two hemispherical caps), used to find the capsule's base from its
+    /// (center) `RigidBodyPosition` for ground/step/CCD ray casts.
+    pub half_height: f32,
+    /// How many sub-steps to sweep the previous step's motion through when
+    /// it exceeds `radius`.
+    pub ccd_subdivisions: usize,
+    /// Distance kept between the swept hit point and the character so it
+    /// doesn't come to rest touching (and potentially re-penetrating) the
+    /// surface it hit.
+    pub skin_width: f32,
+    /// How far below the capsule base the ground ray is allowed to hit and
+    /// still count as "standing on the ground", on top of `skin_width`.
+    pub step_offset: f32,
+    /// How many extra jumps are available after leaving the ground, e.g. `1`
+    /// for a double jump. Resets whenever the character touches the ground.
+    pub air_jumps: u8,
+    /// Air jumps not yet spent since the character was last grounded.
+    pub air_jumps_remaining: u8,
+    /// Fraction of normal directional control retained while airborne and
+    /// not touching a wall, so players can steer a jump without fully
+    /// redirecting its momentum.
+    pub air_control: f32,
+    /// Contact normal of the wall the character is currently touching,
+    /// `None` when not in contact with one.
+    pub on_wall: Option<Vec3>,
+    /// Rate, in units/s^2, the horizontal velocity moves towards a faster
+    /// desired velocity while grounded.
+    pub acceleration: f32,
+    /// Rate, in units/s^2, the horizontal velocity moves towards a slower
+    /// (or zero) desired velocity.
+    pub deceleration: f32,
+    /// Rate, in units/s^2, used instead of `acceleration` while airborne,
+    /// typically much lower so jumps aren't fully steerable.
+    pub air_acceleration: f32,
+    /// Skip the acceleration/deceleration smoothing and snap the horizontal
+    /// velocity straight to its desired value, matching this controller's
+    /// previous behavior.
+    pub instant_velocity: bool,
 }
 
 impl Default for CharacterController {
@@ -82,10 +197,33 @@ impl Default for CharacterController {
             dt: 1.0 / 60.0,
             sim_to_render: 0.0,
             input_state: InputState::default(),
+            radius: 0.4,
+            half_height: 0.5,
+            ccd_subdivisions: 4,
+            skin_width: 0.02,
+            step_offset: 0.3,
+            air_jumps: 1,
+            air_jumps_remaining: 1,
+            air_control: 0.3,
+            on_wall: None,
+            acceleration: 20.0,
+            deceleration: 25.0,
+            air_acceleration: 5.0,
+            instant_velocity: false,
         }
     }
 }
 
+impl CharacterController {
+    /// The capsule's lowest point, given the `RigidBodyPosition` translation
+    /// (its center). Ray casts should start here rather than at the center
+    /// so they don't need to travel the capsule's own height/radius before
+    /// reaching the ground or a nearby obstacle.
+    pub fn capsule_base(&self, center: Vec3) -> Vec3 {
+        center - Vec3::new(0.0, self.half_height + self.radius, 0.0)
+    }
+}
+
 pub struct Mass {
     pub mass: f32,
 }
@@ -98,15 +236,32 @@ impl Mass {
 
 pub fn input_to_events(
     time: Res<Time>,
+    global_step: Res<GlobalStep>,
+    // Only present when a rapier plugin is installed (e.g. not in the PhysX
+    // examples) - the step-climbing query below degrades to a no-op without
+    // it rather than panicking on a missing resource.
+    query_pipeline: Option<Res<QueryPipeline>>,
     keyboard_input: Res<Input<KeyCode>>,
     mut translation_events: EventWriter<TranslationEvent>,
     mut impulse_events: EventWriter<ImpulseEvent>,
     mut force_events: EventWriter<ForceEvent>,
-    mut controller_query: Query<(&Mass, &LookEntity, &mut CharacterController)>,
+    gamepad_axis: Res<Axis<GamepadAxis>>,
+    gamepad_button: Res<Input<GamepadButton>>,
+    gamepad_button_axis: Res<Axis<GamepadButton>>,
+    mut controller_query: Query<(
+        Entity,
+        &Mass,
+        &LookEntity,
+        &mut CharacterController,
+        &Grounded,
+        Option<&RigidBodyPosition>,
+    )>,
     look_direction_query: Query<&LookDirection>,
 ) {
     let xz = Vec3::new(1.0, 0.0, 1.0);
-    for (mass, look_entity, mut controller) in controller_query.iter_mut() {
+    for (entity, mass, look_entity, mut controller, grounded, rigid_body_position) in
+        controller_query.iter_mut()
+    {
         controller.sim_to_render += time.delta_seconds();
 
         if keyboard_input.pressed(controller.input_map.key_forward) {
@@ -128,6 +283,40 @@ pub fn input_to_events(
             controller.input_state.jump = true;
         }
 
+        if let Some(gamepad) = controller.input_map.gamepad {
+            let x = gamepad_axis
+                .get(GamepadAxis(gamepad, controller.input_map.gamepad_stick_x))
+                .unwrap_or(0.0);
+            let y = gamepad_axis
+                .get(GamepadAxis(gamepad, controller.input_map.gamepad_stick_y))
+                .unwrap_or(0.0);
+            // Gamepad sticks report negative Y for "forward" (away from the
+            // player), but `analog_move` is documented in the `(right,
+            // forward)` plane, so flip it here to match.
+            let stick = Vec2::new(x, -y);
+            if stick.length() > controller.input_map.gamepad_stick_deadzone
+                && stick.length() > controller.input_state.analog_move.length()
+            {
+                controller.input_state.analog_move = stick;
+            }
+            if gamepad_button.just_pressed(GamepadButton(
+                gamepad,
+                controller.input_map.gamepad_button_jump,
+            )) {
+                controller.input_state.jump = true;
+            }
+            let run_button = GamepadButton(gamepad, controller.input_map.gamepad_button_run);
+            let run_pressure = gamepad_button_axis.get(run_button).unwrap_or(
+                if gamepad_button.pressed(run_button) {
+                    1.0
+                } else {
+                    0.0
+                },
+            );
+            controller.input_state.analog_run =
+                controller.input_state.analog_run.max(run_pressure.clamp(0.0, 1.0));
+        }
+
         if controller.sim_to_render < controller.dt {
             continue;
         }
@@ -165,30 +354,146 @@ pub fn input_to_events(
             desired_velocity -= right;
         }
 
-        // Limit x/z velocity to walk/run speed
-        let speed = if controller.input_state.run {
-            controller.run_speed
+        // Digital input (keyboard) is always full magnitude; analog input
+        // (gamepad stick) contributes proportionally so a partial tilt
+        // yields a proportionally slower walk rather than snapping to speed.
+        // `right`/`forward` are unit and orthogonal, so this sum's length is
+        // already `analog_move`'s length - the combined desired_velocity is
+        // clamped to length 1 below, so there's no need to (and mustn't)
+        // scale by that magnitude a second time here.
+        desired_velocity += right * controller.input_state.analog_move.x
+            + forward * controller.input_state.analog_move.y;
+
+        // Limit x/z velocity to walk/run speed, blending towards run_speed
+        // as an analog run trigger/button is pressed further
+        let speed = if controller.input_state.run || controller.input_state.analog_run > 0.0 {
+            let run_blend = controller
+                .input_state
+                .analog_run
+                .max(controller.input_state.run as u8 as f32);
+            controller.walk_speed + (controller.run_speed - controller.walk_speed) * run_blend
         } else {
             controller.walk_speed
         };
         desired_velocity = if desired_velocity.length_squared() > 1E-6 {
-            desired_velocity.normalize() * speed
+            desired_velocity.clamp_length_max(1.0) * speed
         } else {
             // No input - apply damping to the x/z of the current velocity
             controller.velocity * 0.5 * xz
         };
 
-        // Handle jumping
+        // Follow the slope instead of walking straight through it. This (and
+        // the air-control/air-jump branch below) trusts `grounded.on_ground`
+        // as a real grounded signal - `detect_ground` now defaults it to
+        // `true` for characters or worlds it can't ray cast against, rather
+        // than leaving them stuck reporting airborne forever, so backends
+        // without rapier's query pipeline don't get stuck walking at
+        // `air_control` speed with only one air jump for the whole session.
+        if grounded.on_ground {
+            desired_velocity = desired_velocity
+                - desired_velocity.dot(grounded.ground_normal) * grounded.ground_normal;
+            controller.air_jumps_remaining = controller.air_jumps;
+            controller.on_wall = None;
+        } else {
+            // Airborne: look for a wall to wall-jump off of or slide down by
+            // casting short rays out towards the character's facing
+            // directions. Only possible with a rapier query backend
+            // installed - otherwise there's simply never a wall to find.
+            controller.on_wall = query_pipeline.as_deref().zip(rigid_body_position).and_then(
+                |(query_pipeline, rigid_body_position)| {
+                    let origin =
+                        controller.capsule_base(rigid_body_position.position.translation.vector)
+                            + Vec3::new(0.0, controller.half_height, 0.0);
+                    let exclude_self = |candidate: Entity| candidate != entity;
+                    [forward, -forward, right, -right].iter().find_map(|dir| {
+                        query_pipeline
+                            .cast_ray_and_get_normal(
+                                &[origin.x, origin.y, origin.z].into(),
+                                &[dir.x, dir.y, dir.z].into(),
+                                controller.radius + 0.2,
+                                true,
+                                InteractionGroups::all(),
+                                Some(&exclude_self),
+                            )
+                            .map(|(_collider, intersection)| {
+                                Vec3::new(
+                                    intersection.normal.x,
+                                    intersection.normal.y,
+                                    intersection.normal.z,
+                                )
+                            })
+                    })
+                },
+            );
+
+            // Steer gradually instead of snapping to the new direction, so a
+            // jump keeps most of its momentum instead of being fully
+            // redirectable mid-air.
+            let air_velocity = controller.velocity * xz;
+            desired_velocity =
+                air_velocity + (desired_velocity - air_velocity) * controller.air_control;
+        }
+
+        // Handle jumping, allowing a short window after leaving the ground
+        // (coyote time) so a jump just after walking off a ledge still fires
+        let can_jump =
+            grounded.on_ground || time.seconds_since_startup() as f32 - grounded.last_ground_time < COYOTE_TIME;
+        let wall_jump_normal = controller
+            .on_wall
+            .filter(|normal| desired_velocity.dot(*normal) < 0.0);
         let was_jumping = controller.jumping;
-        desired_velocity.y = if controller.input_state.jump {
+        desired_velocity.y = if controller.input_state.jump && can_jump {
+            controller.jumping = true;
+            controller.jump_speed
+        } else if let Some(normal) = controller.input_state.jump.then(|| wall_jump_normal).flatten()
+        {
+            // Wall jump: reflect the horizontal velocity off the wall and
+            // add the usual upward jump speed.
+            let reflected = desired_velocity - 2.0 * desired_velocity.dot(normal) * normal;
+            desired_velocity.x = reflected.x;
+            desired_velocity.z = reflected.z;
+            controller.jumping = true;
+            controller.on_wall = None;
+            controller.jump_speed
+        } else if controller.input_state.jump && controller.air_jumps_remaining > 0 {
+            controller.air_jumps_remaining -= 1;
             controller.jumping = true;
             controller.jump_speed
         } else {
             0.0
         };
 
+        // Move the horizontal velocity towards the desired velocity at a
+        // limited rate rather than snapping straight to it, for weightier,
+        // tunable movement. `instant_velocity` restores the old behavior.
+        let current_horizontal = controller.velocity * xz;
+        let desired_horizontal = desired_velocity * xz;
+        let horizontal_delta = desired_horizontal - current_horizontal;
+        let smoothed_horizontal = if controller.instant_velocity || horizontal_delta == Vec3::ZERO
+        {
+            desired_horizontal
+        } else {
+            let accelerating = desired_horizontal.length() >= current_horizontal.length();
+            let rate = if accelerating {
+                if grounded.on_ground {
+                    controller.acceleration
+                } else {
+                    controller.air_acceleration
+                }
+            } else {
+                controller.deceleration
+            };
+            let max_delta = rate * controller.dt;
+            if horizontal_delta.length() > max_delta {
+                current_horizontal + horizontal_delta.normalize() * max_delta
+            } else {
+                desired_horizontal
+            }
+        };
+
         // Calculate impulse - the desired momentum change for the time period
-        let delta_velocity = desired_velocity - controller.velocity * xz;
+        let delta_velocity =
+            (smoothed_horizontal + desired_velocity.y * Vec3::Y) - controller.velocity;
         let impulse = delta_velocity * mass.mass;
         if impulse.length_squared() > 1E-6 {
             impulse_events.send(ImpulseEvent::new(&impulse));
@@ -200,11 +505,16 @@ pub fn input_to_events(
             force_events.send(ForceEvent::new(&force));
         }
 
-        controller.velocity.x = desired_velocity.x;
-        controller.velocity.z = desired_velocity.z;
+        controller.velocity.x = smoothed_horizontal.x;
+        controller.velocity.z = smoothed_horizontal.z;
         controller.velocity.y = if was_jumping {
-            // Apply gravity for kinematic simulation
-            (-9.81f32).mul_add(controller.dt, controller.velocity.y)
+            // Apply gravity for kinematic simulation, reduced while sliding
+            // down a wall the character is holding into
+            let gravity_scale = match wall_jump_normal {
+                Some(_) => 0.3,
+                None => 1.0,
+            };
+            (-9.81f32 * gravity_scale).mul_add(controller.dt, controller.velocity.y)
         } else {
             desired_velocity.y
         };
@@ -214,10 +524,229 @@ pub fn input_to_events(
             translation_events.send(TranslationEvent::new(&translation));
         }
 
+        // Step over low obstacles: if the horizontal move is blocked but a
+        // ray at `GlobalStep` height over the same move is clear, lift the
+        // character onto the step instead of stopping at the wall. Only
+        // possible with a rapier query backend installed.
+        if let (Some(query_pipeline), true) = (
+            query_pipeline.as_deref(),
+            grounded.on_ground && translation.length_squared() > 1E-6,
+        ) {
+            if let Some(rigid_body_position) = rigid_body_position {
+                let base = controller.capsule_base(rigid_body_position.position.translation.vector);
+                let horizontal = translation * xz;
+                let dir = horizontal.normalize();
+                let exclude_self = |candidate: Entity| candidate != entity;
+                let blocked_at_feet = query_pipeline
+                    .cast_ray(
+                        &[base.x, base.y, base.z].into(),
+                        &[dir.x, dir.y, dir.z].into(),
+                        horizontal.length(),
+                        true,
+                        InteractionGroups::all(),
+                        Some(&exclude_self),
+                    )
+                    .is_some();
+                let clear_at_step = query_pipeline
+                    .cast_ray(
+                        &[base.x, base.y + global_step.0, base.z].into(),
+                        &[dir.x, dir.y, dir.z].into(),
+                        horizontal.length(),
+                        true,
+                        InteractionGroups::all(),
+                        Some(&exclude_self),
+                    )
+                    .is_none();
+                if blocked_at_feet && clear_at_step {
+                    translation_events.send(TranslationEvent::new(&(global_step.0 * Vec3::Y)));
+                }
+            }
+        }
+
         controller.input_state = InputState::default();
     }
 }
 
+/// Cast a short ray straight down from each character's feet to find out
+/// whether it's standing on something, and what that surface's normal is, so
+/// `input_to_events` can gate jumping and make the character follow slopes.
+///
+/// Characters with no `RigidBodyPosition` (e.g. the PhysX examples, which
+/// use their own controller and collision rather than rapier's) and worlds
+/// with no rapier query backend at all have nothing for this to ray cast
+/// against; they're left grounded rather than permanently airborne, since
+/// that's the closer match to a backend that's doing its own ground
+/// handling out of this crate's sight.
+pub fn detect_ground(
+    time: Res<Time>,
+    query_pipeline: Option<Res<QueryPipeline>>,
+    mut query: Query<(Entity, &CharacterController, Option<&RigidBodyPosition>, &mut Grounded)>,
+) {
+    for (entity, controller, rigid_body_position, mut grounded) in query.iter_mut() {
+        let hit = query_pipeline.as_deref().zip(rigid_body_position).and_then(
+            |(query_pipeline, rigid_body_position)| {
+                let origin = controller.capsule_base(rigid_body_position.position.translation.vector);
+                let max_toi = controller.skin_width + controller.step_offset;
+                let exclude_self = |candidate: Entity| candidate != entity;
+                query_pipeline.cast_ray_and_get_normal(
+                    &[origin.x, origin.y, origin.z].into(),
+                    &[0.0, -1.0, 0.0].into(),
+                    max_toi,
+                    true,
+                    InteractionGroups::all(),
+                    Some(&exclude_self),
+                )
+            },
+        );
+        match hit {
+            Some((_collider, intersection)) => {
+                grounded.on_ground = true;
+                grounded.ground_normal =
+                    Vec3::new(intersection.normal.x, intersection.normal.y, intersection.normal.z);
+                grounded.last_ground_time = time.seconds_since_startup() as f32;
+            }
+            None if query_pipeline.is_none() || rigid_body_position.is_none() => {
+                grounded.on_ground = true;
+                grounded.ground_normal = Vec3::Y;
+                grounded.last_ground_time = time.seconds_since_startup() as f32;
+            }
+            None => {
+                grounded.on_ground = false;
+                grounded.ground_normal = Vec3::Y;
+            }
+        }
+    }
+}
+
+/// Stop fast-moving characters from tunnelling through thin colliders by
+/// sweeping a ray along the motion implied by the character's velocity on
+/// the *previous* simulation step and clamping this step's `TranslationEvent`
+/// to the first surface hit, independent of whatever physics backend ends up
+/// consuming the (possibly clamped) event.
+///
+/// Runs immediately after `input_to_events` so it sees the translation
+/// proposed for this step before anything downstream applies it. Uses its
+/// own `EventReader` rather than the shared `ControllerEvents` reader, since
+/// exactly one downstream system (the physics backend's translation
+/// consumer) also needs to read every `TranslationEvent` and must not find
+/// the cursor already advanced past them.
+pub fn mitigate_tunneling(
+    mut commands: Commands,
+    // Only present when a rapier plugin is installed (e.g. not in the PhysX
+    // examples) - there's nothing to sweep against without it, so leave
+    // translations alone rather than panicking on a missing resource.
+    query_pipeline: Option<Res<QueryPipeline>>,
+    rigid_body_positions: Query<&RigidBodyPosition>,
+    mut translation_reader: EventReader<TranslationEvent>,
+    mut correction_events: EventWriter<TranslationEvent>,
+    mut controller_query: Query<(
+        Entity,
+        &mut CharacterController,
+        &mut PreviousVelocity,
+        Option<&mut Tunneling>,
+    )>,
+) {
+    let query_pipeline = match query_pipeline {
+        Some(query_pipeline) => query_pipeline,
+        None => return,
+    };
+
+    // NOTE: `TranslationEvent` carries no source entity, so this sum (like
+    // every other consumer of the event) only reflects a single controlled
+    // character correctly; with more than one `CharacterController` it's
+    // applied as if it were each entity's own translation. Fixing that needs
+    // `TranslationEvent` itself to carry an `Entity`, which lives in the
+    // `events` module this chunk doesn't have in front of it.
+    let mut translation = Vec3::ZERO;
+    for event in translation_reader.iter() {
+        translation += **event;
+    }
+
+    for (entity, mut controller, mut previous_velocity, tunneling) in controller_query.iter_mut() {
+        // Previous-velocity bookkeeping and the sweep below are driven by
+        // this entity's own last-frame velocity, not by whether this frame's
+        // (possibly zero, possibly belonging to another entity) aggregate
+        // translation happens to be non-zero, so there's no early return
+        // here: skipping it would also skip updating `previous_velocity`,
+        // silently breaking the sweep on the very next frame.
+        if let Some(mut tunneling) = tunneling {
+            if tunneling.frames > 0 {
+                // Keep the character sliding along the surface it latched
+                // onto rather than re-running the full sweep every frame,
+                // which is what causes the jitter this is meant to avoid.
+                // `tunneling.dir` holds the surface normal, so subtracting
+                // its component removes the penetrating motion while
+                // leaving the tangential (sliding) motion intact.
+                let constrained = translation - translation.dot(tunneling.dir) * tunneling.dir;
+                if constrained != translation {
+                    correction_events.send(TranslationEvent::new(&(constrained - translation)));
+                }
+                tunneling.frames -= 1;
+            }
+        }
+
+        // Drive the sweep off the velocity the character already had last
+        // step, not the translation it's about to make this step, so a body
+        // that was already moving fast enough to tunnel keeps getting swept
+        // even on a step where its own translation happens to be small.
+        let motion = previous_velocity.0 * controller.dt;
+        let distance = motion.length();
+        previous_velocity.0 = controller.velocity;
+        if distance <= controller.radius {
+            continue;
+        }
+
+        let dir = motion.normalize();
+        let subdivisions = controller.ccd_subdivisions.max(1);
+
+        if let Ok(rigid_body_position) = rigid_body_positions.get(entity) {
+            let origin = controller.capsule_base(rigid_body_position.position.translation.vector);
+            let exclude_self = |candidate: Entity| candidate != entity;
+            // Sweep in `subdivisions` casts of growing length, all from the
+            // same origin, and stop at the first one that hits; the last
+            // cast covers the full distance.
+            let mut hit = None;
+            for sub_step in 1..=subdivisions {
+                let sub_distance = distance * sub_step as f32 / subdivisions as f32;
+                if let Some((_collider, intersection)) = query_pipeline.cast_ray_and_get_normal(
+                    &[origin.x, origin.y, origin.z].into(),
+                    &[dir.x, dir.y, dir.z].into(),
+                    sub_distance,
+                    true,
+                    InteractionGroups::all(),
+                    Some(&exclude_self),
+                ) {
+                    hit = Some(intersection);
+                    break;
+                }
+            }
+
+            if let Some(intersection) = hit {
+                let clamped_distance = (intersection.toi - controller.skin_width).max(0.0);
+                let clamped_translation = dir * clamped_distance;
+                if clamped_translation != translation {
+                    correction_events
+                        .send(TranslationEvent::new(&(clamped_translation - translation)));
+                }
+
+                let normal = Vec3::new(
+                    intersection.normal.x,
+                    intersection.normal.y,
+                    intersection.normal.z,
+                );
+                if normal.y > 0.7 {
+                    controller.jumping = false;
+                }
+
+                commands.entity(entity).insert(Tunneling {
+                    frames: 15,
+                    dir: normal,
+                });
+            }
+        }
+    }
+}
+
 pub fn controller_to_yaw(
     mut reader: ResMut<ControllerEvents>,
     yaws: Res<Events<YawEvent>>,
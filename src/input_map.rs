@@ -0,0 +1,44 @@
+use bevy::{
+    input::gamepad::{GamepadAxisType, GamepadButtonType},
+    prelude::*,
+};
+
+/// Maps physical inputs (keyboard, gamepad) onto the logical actions
+/// `input_to_events` reads from `CharacterController.input_state`.
+pub struct InputMap {
+    pub key_forward: KeyCode,
+    pub key_backward: KeyCode,
+    pub key_left: KeyCode,
+    pub key_right: KeyCode,
+    pub key_run: KeyCode,
+    pub key_jump: KeyCode,
+
+    pub gamepad: Option<Gamepad>,
+    pub gamepad_stick_x: GamepadAxisType,
+    pub gamepad_stick_y: GamepadAxisType,
+    pub gamepad_button_run: GamepadButtonType,
+    pub gamepad_button_jump: GamepadButtonType,
+    /// Stick positions below this magnitude are treated as no input, so a
+    /// worn or slightly miscalibrated stick doesn't cause drift.
+    pub gamepad_stick_deadzone: f32,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        Self {
+            key_forward: KeyCode::W,
+            key_backward: KeyCode::S,
+            key_left: KeyCode::A,
+            key_right: KeyCode::D,
+            key_run: KeyCode::LShift,
+            key_jump: KeyCode::Space,
+
+            gamepad: None,
+            gamepad_stick_x: GamepadAxisType::LeftStickX,
+            gamepad_stick_y: GamepadAxisType::LeftStickY,
+            gamepad_button_run: GamepadButtonType::LeftTrigger2,
+            gamepad_button_jump: GamepadButtonType::South,
+            gamepad_stick_deadzone: 0.15,
+        }
+    }
+}
@@ -0,0 +1,6 @@
+pub mod controller;
+pub mod events;
+pub mod input_map;
+pub mod look;
+pub mod physx;
+pub mod rapier;
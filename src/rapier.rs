@@ -0,0 +1,150 @@
+/*
+ * bevy_rapier3d character controller plugins
+ *
+ * Mirrors the PhysX plugins in `physx`, giving users the same three control
+ * modes (kinematic translation, dynamic impulse, dynamic force) driven off
+ * the same `TranslationEvent`/`ImpulseEvent`/`ForceEvent` pipeline, but
+ * applied against bevy_rapier3d instead.
+ */
+
+use crate::{
+    controller::{CharacterController, Grounded, Mass},
+    events::{ControllerEvents, ForceEvent, ImpulseEvent, TranslationEvent},
+};
+use bevy::{app::Events, prelude::*};
+use bevy_rapier3d::prelude::{
+    InteractionGroups, QueryPipeline, RigidBodyForces, RigidBodyPosition, RigidBodyVelocity,
+};
+
+pub struct RapierKinematicTranslationCharacterControllerPlugin;
+
+impl Plugin for RapierKinematicTranslationCharacterControllerPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_to_stage(
+            bevy::app::CoreStage::Update,
+            controller_to_rapier_kinematic.system(),
+        );
+    }
+}
+
+pub struct RapierDynamicImpulseCharacterControllerPlugin;
+
+impl Plugin for RapierDynamicImpulseCharacterControllerPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_to_stage(
+            bevy::app::CoreStage::Update,
+            controller_to_rapier_dynamic_impulse.system(),
+        );
+    }
+}
+
+pub struct RapierDynamicForceCharacterControllerPlugin;
+
+impl Plugin for RapierDynamicForceCharacterControllerPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_to_stage(
+            bevy::app::CoreStage::Update,
+            controller_to_rapier_dynamic_force.system(),
+        );
+    }
+}
+
+/// Move the character by the desired translation with a small move-and-slide:
+/// cast along the motion (with the same ray query `mitigate_tunneling` uses
+/// for CCD), clamp to the first surface hit (minus `skin_width`) and slide
+/// the remainder along it, rather than teleporting straight through
+/// whatever's in the way.
+///
+/// This crate's rapier integration is still on the old component API
+/// (`RigidBodyPosition`, `QueryPipeline::cast_ray_and_get_normal`, as used by
+/// `detect_ground` and `mitigate_tunneling`), which predates and cannot
+/// coexist in the same bevy_rapier3d version with the newer
+/// `KinematicCharacterController`/`KinematicCharacterControllerOutput` that
+/// crate's own move-and-slide solver is built on - adopting that API here
+/// would mean migrating every other rapier integration point in this crate
+/// at the same time, so this rolls a small move-and-slide out of the
+/// `QueryPipeline` primitives already in use instead of pulling in an
+/// incompatible API generation for this one system.
+///
+/// Reads back grounded state from the `Grounded` component `detect_ground`
+/// already maintains, rather than a rapier-reported controller output, since
+/// that component belongs to the newer API this can't use anyway.
+pub fn controller_to_rapier_kinematic(
+    translations: Res<Events<TranslationEvent>>,
+    mut reader: ResMut<ControllerEvents>,
+    query_pipeline: Res<QueryPipeline>,
+    mut query: Query<(Entity, &mut RigidBodyPosition, &mut CharacterController, &Grounded)>,
+) {
+    let mut translation = Vec3::ZERO;
+    for event in reader.translations.iter(&translations) {
+        translation += **event;
+    }
+    if translation.length_squared() < 1E-6 {
+        return;
+    }
+
+    for (entity, mut rigid_body_position, mut controller, grounded) in query.iter_mut() {
+        let exclude_self = |candidate: Entity| candidate != entity;
+        let origin = controller.capsule_base(rigid_body_position.position.translation.vector);
+        let distance = translation.length();
+        let dir = translation.normalize();
+        let moved = match query_pipeline.cast_ray_and_get_normal(
+            &[origin.x, origin.y, origin.z].into(),
+            &[dir.x, dir.y, dir.z].into(),
+            distance,
+            true,
+            InteractionGroups::all(),
+            Some(&exclude_self),
+        ) {
+            Some((_collider, intersection)) => {
+                let clamped = dir * (intersection.toi - controller.skin_width).max(0.0);
+                let normal = Vec3::new(
+                    intersection.normal.x,
+                    intersection.normal.y,
+                    intersection.normal.z,
+                );
+                // Slide the remaining motion along the hit surface instead
+                // of stopping dead against it.
+                let remainder = translation - clamped;
+                clamped + (remainder - remainder.dot(normal) * normal)
+            }
+            None => translation,
+        };
+        rigid_body_position.position.translation.vector += moved.into();
+        if grounded.on_ground {
+            controller.jumping = false;
+        }
+    }
+}
+
+pub fn controller_to_rapier_dynamic_impulse(
+    impulses: Res<Events<ImpulseEvent>>,
+    mut reader: ResMut<ControllerEvents>,
+    mut query: Query<(&mut RigidBodyVelocity, &Mass), With<CharacterController>>,
+) {
+    let mut impulse = Vec3::ZERO;
+    for event in reader.impulses.iter(&impulses) {
+        impulse += **event;
+    }
+    if impulse.length_squared() > 1E-6 {
+        for (mut rigid_body_velocity, mass) in query.iter_mut() {
+            rigid_body_velocity.linvel += (impulse / mass.mass).into();
+        }
+    }
+}
+
+pub fn controller_to_rapier_dynamic_force(
+    forces: Res<Events<ForceEvent>>,
+    mut reader: ResMut<ControllerEvents>,
+    mut query: Query<&mut RigidBodyForces, With<CharacterController>>,
+) {
+    let mut force = Vec3::ZERO;
+    for event in reader.forces.iter(&forces) {
+        force += **event;
+    }
+    if force.length_squared() > 1E-6 {
+        for mut rigid_body_forces in query.iter_mut() {
+            rigid_body_forces.force += force.into();
+        }
+    }
+}